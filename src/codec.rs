@@ -0,0 +1,132 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+// A compact binary codec for histogram snapshots: a flat `Vec<u64>` with a
+// two-word header (histogram type, sum) followed by interleaved
+// `(bucket_min, count)` pairs, avoiding JSON parsing at the WASM/JS boundary.
+
+use std::collections::BTreeMap;
+
+use crate::snapshot::Parsed;
+
+const TYPE_FUNCTIONAL: u64 = 0;
+const TYPE_PRECOMPUTED: u64 = 1;
+
+/// Encodes a bucket-minimum -> count map as `[type_tag, sum, bucket_min_0,
+/// count_0, bucket_min_1, count_1, ...]`.
+fn encode(type_tag: u64, sum: u64, values: &BTreeMap<u64, u64>) -> Vec<u64> {
+    let mut bytes = Vec::with_capacity(2 + values.len() * 2);
+    bytes.push(type_tag);
+    bytes.push(sum);
+    for (&bucket_min, &count) in values.iter() {
+        bytes.push(bucket_min);
+        bytes.push(count);
+    }
+    bytes
+}
+
+/// Decodes the flat layout produced by [`encode`] back into a [`Parsed`]
+/// snapshot.
+pub(crate) fn decode(bytes: &[u64]) -> Parsed {
+    assert!(bytes.len() >= 2, "Malformed snapshot bytes: missing header");
+    let type_tag = bytes[0];
+    let sum = bytes[1];
+    let pairs = &bytes[2..];
+    assert_eq!(
+        pairs.len() % 2,
+        0,
+        "Malformed snapshot bytes: odd number of pair entries"
+    );
+
+    let mut values = BTreeMap::new();
+    for pair in pairs.chunks_exact(2) {
+        values.insert(pair[0], pair[1]);
+    }
+
+    match type_tag {
+        TYPE_FUNCTIONAL => Parsed::Functional { sum, values },
+        TYPE_PRECOMPUTED => Parsed::Precomputed(values),
+        other => panic!("Unknown histogram type tag in snapshot bytes: {other}"),
+    }
+}
+
+/// Encodes a functional histogram's bucket map, carrying its exact `sum`.
+pub(crate) fn encode_functional(sum: u64, values: &BTreeMap<u64, u64>) -> Vec<u64> {
+    encode(TYPE_FUNCTIONAL, sum, values)
+}
+
+/// Encodes a precomputed (linear/exponential) histogram's bucket map, whose
+/// sum falls back to the same bucket-representative approximation as
+/// `snapshot::stats`.
+pub(crate) fn encode_precomputed(values: &BTreeMap<u64, u64>) -> Vec<u64> {
+    let sum = values.iter().map(|(&bucket_min, &count)| bucket_min * count).sum();
+    encode(TYPE_PRECOMPUTED, sum, values)
+}
+
+/// Encodes an already-parsed snapshot, picking the right type tag.
+pub(crate) fn encode_parsed(parsed: &Parsed) -> Vec<u64> {
+    match parsed {
+        Parsed::Functional { sum, values } => encode_functional(*sum, values),
+        Parsed::Precomputed(values) => encode_precomputed(values),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn functional_round_trips_through_bytes_with_exact_sum() {
+        let mut values = BTreeMap::new();
+        values.insert(5, 2);
+        values.insert(10, 1);
+
+        let bytes = encode_functional(123456, &values);
+        let decoded = decode(&bytes);
+
+        assert!(matches!(decoded, Parsed::Functional { sum: 123456, .. }));
+        assert_eq!(decoded.values(), &values);
+    }
+
+    #[test]
+    fn precomputed_round_trips_through_bytes() {
+        let mut values = BTreeMap::new();
+        values.insert(0, 3);
+        values.insert(90, 1);
+
+        let bytes = encode_precomputed(&values);
+        let decoded = decode(&bytes);
+
+        assert!(matches!(decoded, Parsed::Precomputed(_)));
+        assert_eq!(decoded.values(), &values);
+    }
+
+    #[test]
+    fn encode_parsed_preserves_the_type_tag() {
+        let parsed = Parsed::Functional {
+            sum: 7,
+            values: BTreeMap::from([(1, 1)]),
+        };
+        let decoded = decode(&encode_parsed(&parsed));
+        assert!(matches!(decoded, Parsed::Functional { sum: 7, .. }));
+    }
+
+    #[test]
+    #[should_panic(expected = "missing header")]
+    fn decode_rejects_too_short_input() {
+        decode(&[0]);
+    }
+
+    #[test]
+    #[should_panic(expected = "odd number of pair entries")]
+    fn decode_rejects_unpaired_trailing_entry() {
+        decode(&[0, 0, 5, 2, 10]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Unknown histogram type tag")]
+    fn decode_rejects_unknown_type_tag() {
+        decode(&[2, 0]);
+    }
+}
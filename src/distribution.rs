@@ -0,0 +1,319 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+// Stateful wrappers around `Histogram` that keep accumulating across calls
+// from JavaScript, instead of the one-shot `accumulate_samples_*` functions
+// in `lib.rs` which rebuild the histogram from scratch on every call.
+
+use std::convert::TryFrom;
+
+use wasm_bindgen::prelude::*;
+
+use crate::histogram::{Bucketing, Histogram, HistogramType};
+use crate::units::{MemoryUnit, TimeUnit};
+
+// The base of the logarithm used to determine bucketing for timing
+// distributions.
+const TIMING_LOG_BASE: f64 = 2.0;
+// The buckets per each order of magnitude of the logarithm for timing
+// distributions.
+const TIMING_BUCKETS_PER_MAGNITUDE: f64 = 8.0;
+// Maximum time, which means we retain a maximum of 316 buckets.
+const MAX_SAMPLE_TIME: u64 = 1000 * 1000 * 1000 * 60 * 10;
+
+// The base of the logarithm used to determine bucketing for memory
+// distributions.
+const MEMORY_LOG_BASE: f64 = 2.0;
+// The buckets per each order of magnitude of the logarithm for memory
+// distributions.
+const MEMORY_BUCKETS_PER_MAGNITUDE: f64 = 16.0;
+// Set a maximum recordable value of 1 terabyte so the buckets aren't
+// completely unbounded.
+const MAX_BYTES: u64 = 1 << 40;
+
+/// A persistent timing distribution histogram.
+#[wasm_bindgen]
+pub struct TimingDistribution {
+    time_unit: TimeUnit,
+    hist: Histogram<crate::histogram::Functional>,
+}
+
+#[wasm_bindgen]
+impl TimingDistribution {
+    #[wasm_bindgen(constructor)]
+    pub fn new(time_unit: i32) -> TimingDistribution {
+        TimingDistribution {
+            time_unit: TimeUnit::try_from(time_unit).expect("Invalid value for time_unit!"),
+            hist: Histogram::functional(TIMING_LOG_BASE, TIMING_BUCKETS_PER_MAGNITUDE),
+        }
+    }
+
+    /// Accumulates a single sample, given in the distribution's `time_unit`.
+    pub fn accumulate(&mut self, sample: u64) {
+        self.hist.accumulate(self.clamp_and_convert(sample));
+    }
+
+    /// Accumulates a batch of samples, given in the distribution's
+    /// `time_unit`.
+    pub fn accumulate_many(&mut self, samples: Vec<u64>) {
+        for sample in samples {
+            self.accumulate(sample);
+        }
+    }
+
+    /// Like `accumulate`, but for a fractional sample; only truncated to a
+    /// `u64` immediately before accumulating, so sub-unit precision (e.g.
+    /// from `performance.now()`) isn't lost.
+    pub fn accumulate_f64(&mut self, sample: f64) {
+        self.hist.accumulate(self.clamp_and_convert_f64(sample));
+    }
+
+    /// Accumulates a batch of fractional samples, given in the
+    /// distribution's `time_unit`.
+    pub fn accumulate_many_f64(&mut self, samples: Vec<f64>) {
+        for sample in samples {
+            self.accumulate_f64(sample);
+        }
+    }
+
+    /// Serializes the current state of the histogram.
+    pub fn snapshot(&self) -> String {
+        serde_json::to_string(&self.hist.snapshot()).unwrap()
+    }
+
+    /// Serializes the current state of the histogram into the compact
+    /// binary codec, avoiding the cost of JSON encoding on every flush.
+    pub fn snapshot_bytes(&self) -> Vec<u64> {
+        let snapshot = self.hist.snapshot();
+        crate::codec::encode_functional(snapshot.sum, &snapshot.values)
+    }
+
+    fn clamp_and_convert(&self, sample: u64) -> u64 {
+        // Check the range prior to converting the incoming unit to
+        // nanoseconds, so we can compare against the constant
+        // MAX_SAMPLE_TIME.
+        let mut sample = sample;
+        if sample == 0 {
+            sample = 1;
+        } else if sample > MAX_SAMPLE_TIME {
+            sample = MAX_SAMPLE_TIME;
+        }
+
+        self.time_unit.as_nanos(sample)
+    }
+
+    fn clamp_and_convert_f64(&self, sample: f64) -> u64 {
+        // As in `clamp_and_convert`, check the range prior to converting the
+        // incoming unit to nanoseconds, so we can compare against the
+        // constant MAX_SAMPLE_TIME.
+        let mut sample = sample;
+        if sample <= 0.0 {
+            sample = 1.0;
+        } else if sample > MAX_SAMPLE_TIME as f64 {
+            sample = MAX_SAMPLE_TIME as f64;
+        }
+
+        // `TimeUnit` only converts whole `u64`s; reuse it to get the
+        // nanoseconds-per-unit scale factor instead of rounding `sample` to
+        // an integer before converting, which would throw away the
+        // fractional precision this method exists to preserve.
+        let nanos_per_unit = self.time_unit.as_nanos(1) as f64;
+        (sample * nanos_per_unit) as u64
+    }
+}
+
+/// A persistent memory distribution histogram.
+#[wasm_bindgen]
+pub struct MemoryDistribution {
+    memory_unit: MemoryUnit,
+    hist: Histogram<crate::histogram::Functional>,
+}
+
+#[wasm_bindgen]
+impl MemoryDistribution {
+    #[wasm_bindgen(constructor)]
+    pub fn new(memory_unit: i32) -> MemoryDistribution {
+        MemoryDistribution {
+            memory_unit: MemoryUnit::try_from(memory_unit).expect("Invalid value for memory_unit!"),
+            hist: Histogram::functional(MEMORY_LOG_BASE, MEMORY_BUCKETS_PER_MAGNITUDE),
+        }
+    }
+
+    /// Accumulates a single sample, given in the distribution's
+    /// `memory_unit`.
+    pub fn accumulate(&mut self, sample: u64) {
+        let mut sample = self.memory_unit.as_bytes(sample);
+        if sample > MAX_BYTES {
+            sample = MAX_BYTES;
+        }
+        self.hist.accumulate(sample);
+    }
+
+    /// Accumulates a batch of samples, given in the distribution's
+    /// `memory_unit`.
+    pub fn accumulate_many(&mut self, samples: Vec<u64>) {
+        for sample in samples {
+            self.accumulate(sample);
+        }
+    }
+
+    /// Accumulates a single fractional sample, given in the distribution's
+    /// `memory_unit`. The value is only truncated to a `u64` immediately
+    /// before accumulating.
+    pub fn accumulate_f64(&mut self, sample: f64) {
+        // Unlike a `u64` sample, a fractional one can be negative; floor it
+        // at 0 before conversion so it doesn't wrap to a huge value on the
+        // cast to `u64` below.
+        let sample = if sample <= 0.0 { 0.0 } else { sample };
+        let bytes_per_unit = self.memory_unit.as_bytes(1) as f64;
+        let mut sample = (sample * bytes_per_unit) as u64;
+        if sample > MAX_BYTES {
+            sample = MAX_BYTES;
+        }
+        self.hist.accumulate(sample);
+    }
+
+    /// Accumulates a batch of fractional samples, given in the
+    /// distribution's `memory_unit`.
+    pub fn accumulate_many_f64(&mut self, samples: Vec<f64>) {
+        for sample in samples {
+            self.accumulate_f64(sample);
+        }
+    }
+
+    /// Serializes the current state of the histogram.
+    pub fn snapshot(&self) -> String {
+        serde_json::to_string(&self.hist.snapshot()).unwrap()
+    }
+
+    /// Serializes the current state of the histogram into the compact
+    /// binary codec, avoiding the cost of JSON encoding on every flush.
+    pub fn snapshot_bytes(&self) -> Vec<u64> {
+        let snapshot = self.hist.snapshot();
+        crate::codec::encode_functional(snapshot.sum, &snapshot.values)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Unit `0` is each distribution's base unit (nanosecond, byte), so its
+    // conversion factor is 1 and the clamped/converted value can be asserted
+    // on directly.
+
+    #[test]
+    fn clamp_and_convert_f64_floors_non_positive_samples_to_one_unit() {
+        let dist = TimingDistribution::new(0);
+        assert_eq!(dist.clamp_and_convert_f64(0.0), 1);
+        assert_eq!(dist.clamp_and_convert_f64(-5.0), 1);
+    }
+
+    #[test]
+    fn clamp_and_convert_f64_caps_at_max_sample_time() {
+        let dist = TimingDistribution::new(0);
+        assert_eq!(dist.clamp_and_convert_f64(MAX_SAMPLE_TIME as f64 + 1.0), MAX_SAMPLE_TIME);
+    }
+
+    #[test]
+    fn clamp_and_convert_f64_preserves_fractional_precision_within_unit() {
+        // Microseconds: 2.7us is 2700ns, which rounding the sample to a
+        // whole unit before converting would have turned into 3000ns.
+        let dist = TimingDistribution::new(1);
+        assert_eq!(dist.clamp_and_convert_f64(2.7), 2700);
+    }
+
+    #[test]
+    fn memory_distribution_accumulate_f64_clamps_non_positive_samples_to_zero_bytes() {
+        let mut dist = MemoryDistribution::new(0);
+        dist.accumulate_f64(-5.0);
+        assert_eq!(dist.hist.snapshot().values.get(&0), Some(&1));
+    }
+
+    #[test]
+    fn memory_distribution_accumulate_f64_caps_at_max_bytes() {
+        let mut dist = MemoryDistribution::new(0);
+        dist.accumulate_f64(MAX_BYTES as f64 + 100.0);
+        // The bucket holding MAX_BYTES has a minimum just below it, so check
+        // the recorded sum rather than a bucket key.
+        assert_eq!(dist.hist.snapshot().sum, MAX_BYTES);
+    }
+}
+
+// A custom distribution can be bucketed either linearly or exponentially,
+// chosen once at construction time from `histogram_type`. Since the two
+// bucketings are different concrete types, keep them behind an enum rather
+// than forcing callers to pick a generic parameter from JS.
+enum CustomHistogram {
+    Linear(Histogram<crate::histogram::Linear>),
+    Exponential(Histogram<crate::histogram::Exponential>),
+}
+
+/// A persistent custom distribution histogram, linearly or exponentially
+/// bucketed depending on `histogram_type`.
+#[wasm_bindgen]
+pub struct CustomDistribution {
+    hist: CustomHistogram,
+}
+
+#[wasm_bindgen]
+impl CustomDistribution {
+    #[wasm_bindgen(constructor)]
+    pub fn new(
+        range_min: u32,
+        range_max: u32,
+        bucket_count: usize,
+        histogram_type: i32,
+    ) -> CustomDistribution {
+        let range_min = range_min as u64;
+        let range_max = range_max as u64;
+        let hist = match HistogramType::try_from(histogram_type)
+            .expect("Invalid value for histogram_type!")
+        {
+            HistogramType::Linear => {
+                CustomHistogram::Linear(Histogram::linear(range_min, range_max, bucket_count))
+            }
+            HistogramType::Exponential => CustomHistogram::Exponential(Histogram::exponential(
+                range_min,
+                range_max,
+                bucket_count,
+            )),
+        };
+        CustomDistribution { hist }
+    }
+
+    pub fn accumulate(&mut self, sample: u64) {
+        match &mut self.hist {
+            CustomHistogram::Linear(hist) => hist.accumulate(sample),
+            CustomHistogram::Exponential(hist) => hist.accumulate(sample),
+        }
+    }
+
+    pub fn accumulate_many(&mut self, samples: Vec<u64>) {
+        for sample in samples {
+            self.accumulate(sample);
+        }
+    }
+
+    /// Serializes the current state of the histogram.
+    pub fn snapshot(&self) -> String {
+        match &self.hist {
+            CustomHistogram::Linear(hist) => serde_json::to_string(&hist.snapshot_values()).unwrap(),
+            CustomHistogram::Exponential(hist) => {
+                serde_json::to_string(&hist.snapshot_values()).unwrap()
+            }
+        }
+    }
+
+    /// Serializes the current state of the histogram into the compact
+    /// binary codec, avoiding the cost of JSON encoding on every flush.
+    pub fn snapshot_bytes(&self) -> Vec<u64> {
+        match &self.hist {
+            CustomHistogram::Linear(hist) => crate::codec::encode_precomputed(&hist.snapshot_values()),
+            CustomHistogram::Exponential(hist) => {
+                crate::codec::encode_precomputed(&hist.snapshot_values())
+            }
+        }
+    }
+}
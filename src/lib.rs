@@ -4,16 +4,20 @@
 
 use std::convert::TryFrom;
 
+mod codec;
+mod distribution;
 mod error;
 mod histogram;
+mod snapshot;
 mod units;
 mod util;
 
 use crate::histogram::{Bucketing, Histogram, HistogramType};
-use crate::units::{MemoryUnit, TimeUnit};
 
 use wasm_bindgen::prelude::*;
 
+pub use crate::distribution::{CustomDistribution, MemoryDistribution, TimingDistribution};
+
 #[wasm_bindgen]
 pub fn accumulate_samples_custom_distribution(
     range_min: u32,
@@ -45,68 +49,109 @@ pub fn accumulate_samples_custom_distribution(
     }
 }
 
+// Samples are clamped to `MAX_SAMPLE_TIME` (see `distribution.rs`) in the
+// incoming `time_unit`, before conversion to nanoseconds, so the maximum
+// recordable time depends on the `time_unit` parameter:
+//
+// - `nanosecond` - 10 minutes
+// - `microsecond` - ~6.94 days
+// - `millisecond` - ~19 years
 #[wasm_bindgen]
 pub fn accumulate_samples_timing_distribution(time_unit: i32, samples: Vec<u64>) -> String {
-    // The base of the logarithm used to determine bucketing
-    const LOG_BASE: f64 = 2.0;
-
-    // The buckets per each order of magnitude of the logarithm.
-    const BUCKETS_PER_MAGNITUDE: f64 = 8.0;
-
-    // Maximum time, which means we retain a maximum of 316 buckets.
-    // It is automatically adjusted based on the `time_unit` parameter
-    // so that:
-    //
-    // - `nanosecond` - 10 minutes
-    // - `microsecond` - ~6.94 days
-    // - `millisecond` - ~19 years
-    const MAX_SAMPLE_TIME: u64 = 1000 * 1000 * 1000 * 60 * 10;
-
-    let mut hist = Histogram::functional(LOG_BASE, BUCKETS_PER_MAGNITUDE);
-    for &sample in samples.iter() {
-        // Check the range prior to converting the incoming unit to
-        // nanoseconds, so we can compare against the constant
-        // MAX_SAMPLE_TIME.
-        let mut sample = sample as u64;
-        if sample == 0 {
-            sample = 1;
-        } else if sample > MAX_SAMPLE_TIME {
-            sample = MAX_SAMPLE_TIME;
-        }
+    let mut dist = distribution::TimingDistribution::new(time_unit);
+    dist.accumulate_many(samples);
+    dist.snapshot()
+}
 
-        sample = TimeUnit::try_from(time_unit)
-            .expect("Invalid valid for time_unit!")
-            .as_nanos(sample);
-        hist.accumulate(sample as u64);
-    }
+#[wasm_bindgen]
+pub fn accumulate_samples_memory_distribution(memory_unit: i32, samples: Vec<u64>) -> String {
+    let mut dist = distribution::MemoryDistribution::new(memory_unit);
+    dist.accumulate_many(samples);
+    dist.snapshot()
+}
 
-    serde_json::to_string(&hist.snapshot()).unwrap()
+/// Like `accumulate_samples_timing_distribution`, but accepts fractional
+/// samples (e.g. from `performance.now()`) and preserves their precision
+/// down to nanoseconds instead of requiring callers to round beforehand.
+#[wasm_bindgen]
+pub fn accumulate_samples_timing_distribution_f64(time_unit: i32, samples: Vec<f64>) -> String {
+    let mut dist = distribution::TimingDistribution::new(time_unit);
+    dist.accumulate_many_f64(samples);
+    dist.snapshot()
 }
 
+/// Like `accumulate_samples_memory_distribution`, but accepts fractional
+/// samples and preserves their precision instead of requiring callers to
+/// round beforehand.
 #[wasm_bindgen]
-pub fn accumulate_samples_memory_distribution(memory_unit: i32, samples: Vec<u64>) -> String {
-    // The base of the logarithm used to determine bucketing
-    const LOG_BASE: f64 = 2.0;
-
-    // The buckets per each order of magnitude of the logarithm.
-    const BUCKETS_PER_MAGNITUDE: f64 = 16.0;
-
-    // Set a maximum recordable value of 1 terabyte so the buckets aren't
-    // completely unbounded.
-    const MAX_BYTES: u64 = 1 << 40;
-
-    let mut hist = Histogram::functional(LOG_BASE, BUCKETS_PER_MAGNITUDE);
-    for &sample in samples.iter() {
-        let sample = sample as u64;
-        let mut sample = MemoryUnit::try_from(memory_unit)
-            .expect("Invalid valid for memory_unit!")
-            .as_bytes(sample);
-        if sample > MAX_BYTES {
-            sample = MAX_BYTES;
-        }
+pub fn accumulate_samples_memory_distribution_f64(memory_unit: i32, samples: Vec<f64>) -> String {
+    let mut dist = distribution::MemoryDistribution::new(memory_unit);
+    dist.accumulate_many_f64(samples);
+    dist.snapshot()
+}
 
-        hist.accumulate(sample);
-    }
+/// Returns the value at each requested quantile for a serialized histogram
+/// snapshot, as a JSON array in the same order as `quantiles`. The returned
+/// value for a given quantile is the *minimum* of the bucket it falls in,
+/// not an interpolated estimate, so it carries the same bucket-width error
+/// as the histogram itself. A quantile is reported as `null` when the
+/// histogram is empty. `q` is clamped to `[0, 1]`.
+#[wasm_bindgen]
+pub fn percentiles(snapshot_json: &str, quantiles: Vec<f64>) -> String {
+    let values = snapshot::parse_values(snapshot_json).expect("Invalid snapshot JSON!");
+    serde_json::to_string(&snapshot::percentiles(&values, &quantiles)).unwrap()
+}
+
+/// Returns count, sum, mean, min, max, variance and standard deviation for a
+/// serialized histogram snapshot, as a JSON object. These are computed from
+/// bucket representatives since the raw samples are lost after bucketing,
+/// except `sum`, which is exact for functional (timing/memory) snapshots.
+#[wasm_bindgen]
+pub fn distribution_stats(snapshot_json: &str) -> String {
+    let parsed = snapshot::parse(snapshot_json).expect("Invalid snapshot JSON!");
+    serde_json::to_string(&snapshot::stats(&parsed)).unwrap()
+}
+
+/// Merges two serialized histogram snapshots of the same kind, returning a
+/// combined snapshot whose per-bucket counts are the element-wise sums.
+/// Enables accumulating in several web workers and combining results before
+/// upload without re-sending individual samples.
+///
+/// `range_min`/`range_max`/`bucket_count` are only required when merging
+/// linear/exponential snapshots, which don't carry their own bucketing on
+/// the wire: pass the same values both distributions were constructed with.
+/// They're ignored for functional (timing/memory) snapshots.
+#[wasm_bindgen]
+pub fn merge_snapshots(
+    a_json: &str,
+    b_json: &str,
+    range_min: Option<u32>,
+    range_max: Option<u32>,
+    bucket_count: Option<usize>,
+) -> String {
+    let a = snapshot::parse(a_json).expect("Invalid snapshot JSON!");
+    let b = snapshot::parse(b_json).expect("Invalid snapshot JSON!");
+    let range = match (range_min, range_max, bucket_count) {
+        (Some(min), Some(max), Some(count)) => Some((min as u64, max as u64, count)),
+        _ => None,
+    };
+    snapshot::merge(&a, &b, range).to_json()
+}
+
+/// Encodes a serialized histogram snapshot as a compact binary blob: a
+/// small header (histogram type, sum) followed by interleaved
+/// `(bucket_min, count)` pairs. Shrinks transfer size for sparse functional
+/// histograms and gives a stable wire format for storing partial
+/// histograms in IndexedDB.
+#[wasm_bindgen]
+pub fn snapshot_bytes(snapshot_json: &str) -> Vec<u64> {
+    let parsed = snapshot::parse(snapshot_json).expect("Invalid snapshot JSON!");
+    codec::encode_parsed(&parsed)
+}
 
-    serde_json::to_string(&hist.snapshot()).unwrap()
+/// Decodes a binary blob produced by `snapshot_bytes` back into the JSON
+/// snapshot shape.
+#[wasm_bindgen]
+pub fn snapshot_from_bytes(bytes: Vec<u64>) -> String {
+    codec::decode(&bytes).to_json()
 }
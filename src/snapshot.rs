@@ -0,0 +1,339 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+// Helpers for working with already-serialized histogram snapshots: parses
+// both the plain bucket-map and `{sum, values}` JSON shapes into one
+// representation shared by the percentile/stats/merge queries below.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+/// The `{sum, values}` shape produced by `Histogram::snapshot` for
+/// functional (timing/memory) histograms.
+#[derive(Deserialize, Serialize)]
+struct FunctionalSnapshot {
+    sum: u64,
+    values: BTreeMap<u64, u64>,
+}
+
+/// A snapshot parsed from JSON, keeping track of which bucketing produced
+/// it since that changes how two snapshots may be merged. Functional
+/// snapshots carry their exact `sum` from the wire; precomputed ones have
+/// no such field (see `stats`).
+pub(crate) enum Parsed {
+    Functional { sum: u64, values: BTreeMap<u64, u64> },
+    Precomputed(BTreeMap<u64, u64>),
+}
+
+impl Parsed {
+    pub(crate) fn values(&self) -> &BTreeMap<u64, u64> {
+        match self {
+            Parsed::Functional { values, .. } => values,
+            Parsed::Precomputed(values) => values,
+        }
+    }
+
+    /// Re-serializes this snapshot back to the JSON shape it was parsed
+    /// from (the `{sum, values}` wrapper for functional histograms, or a
+    /// plain bucket map for precomputed ones).
+    pub(crate) fn to_json(&self) -> String {
+        match self {
+            Parsed::Functional { sum, values } => {
+                serde_json::to_string(&FunctionalSnapshot {
+                    sum: *sum,
+                    values: values.clone(),
+                })
+                .unwrap()
+            }
+            Parsed::Precomputed(values) => serde_json::to_string(values).unwrap(),
+        }
+    }
+}
+
+/// Parses either snapshot shape.
+pub(crate) fn parse(snapshot_json: &str) -> Result<Parsed, serde_json::Error> {
+    if let Ok(functional) = serde_json::from_str::<FunctionalSnapshot>(snapshot_json) {
+        return Ok(Parsed::Functional {
+            sum: functional.sum,
+            values: functional.values,
+        });
+    }
+    serde_json::from_str::<BTreeMap<u64, u64>>(snapshot_json).map(Parsed::Precomputed)
+}
+
+/// Parses either snapshot shape into a bucket-minimum -> count map, ordered
+/// by ascending bucket minimum.
+pub(crate) fn parse_values(snapshot_json: &str) -> Result<BTreeMap<u64, u64>, serde_json::Error> {
+    parse(snapshot_json).map(|parsed| match parsed {
+        Parsed::Functional { values, .. } => values,
+        Parsed::Precomputed(values) => values,
+    })
+}
+
+/// Merges two parsed snapshots of the same kind, summing per-bucket counts
+/// and, for functional histograms, their exact `sum`s too.
+///
+/// A serialized snapshot carries no `range_min`/`range_max`/`bucket_count`,
+/// so for precomputed (linear/exponential) histograms `range` lets the
+/// caller assert the bucketing both snapshots were built with; it's
+/// required for precomputed/precomputed merges and used to reject any
+/// bucket key that falls outside the range or off the implied grid.
+pub(crate) fn merge(a: &Parsed, b: &Parsed, range: Option<(u64, u64, usize)>) -> Parsed {
+    if let (Parsed::Precomputed(a_values), Parsed::Precomputed(b_values)) = (a, b) {
+        let (range_min, range_max, bucket_count) = range.expect(
+            "Merging precomputed (linear/exponential) snapshots requires the shared \
+             range_min/range_max/bucket_count, since a serialized snapshot doesn't carry its own bucketing",
+        );
+        let span = range_max - range_min;
+        let matches_bucketing = |values: &BTreeMap<u64, u64>| {
+            values.keys().all(|&bucket_min| {
+                if bucket_min < range_min || bucket_min > range_max {
+                    return false;
+                }
+                if span == 0 || bucket_count == 0 {
+                    return bucket_min == range_min;
+                }
+                // `bucket_min` must land exactly on one of the `bucket_count`
+                // evenly spaced boundaries implied by the range, i.e.
+                // `(bucket_min - range_min) / (span / bucket_count)` is a
+                // whole number. Rearranged to avoid integer division so a
+                // non-evenly-dividing width doesn't hide a mismatch.
+                (bucket_min - range_min) * bucket_count as u64 % span == 0
+            })
+        };
+        if !matches_bucketing(a_values) || !matches_bucketing(b_values) {
+            panic!("Cannot merge precomputed histogram snapshots with different ranges or bucket counts!");
+        }
+    }
+
+    let mut merged = a.values().clone();
+    for (&bucket_min, &count) in b.values() {
+        *merged.entry(bucket_min).or_insert(0) += count;
+    }
+
+    match (a, b) {
+        (Parsed::Functional { sum: sum_a, .. }, Parsed::Functional { sum: sum_b, .. }) => {
+            Parsed::Functional {
+                sum: sum_a + sum_b,
+                values: merged,
+            }
+        }
+        (Parsed::Precomputed(_), Parsed::Precomputed(_)) => Parsed::Precomputed(merged),
+        _ => panic!("Cannot merge a functional snapshot with a precomputed one!"),
+    }
+}
+
+/// Computes the value at each requested quantile `q` by finding its target
+/// rank, `ceil(q * total)`, and walking buckets in ascending order until
+/// the running count reaches it. Reports that bucket's minimum rather than
+/// interpolating, so the result carries the same bucket-width error as the
+/// histogram itself.
+///
+/// Returns `None` for a given quantile when the histogram is empty.
+pub(crate) fn percentiles(values: &BTreeMap<u64, u64>, quantiles: &[f64]) -> Vec<Option<u64>> {
+    let total: u64 = values.values().sum();
+
+    quantiles
+        .iter()
+        .map(|&q| {
+            if total == 0 {
+                return None;
+            }
+
+            let q = q.clamp(0.0, 1.0);
+            let target_rank = (q * total as f64).ceil() as u64;
+            let target_rank = target_rank.max(1);
+
+            let mut running = 0u64;
+            for (&bucket_min, &count) in values.iter() {
+                running += count;
+                if running >= target_rank {
+                    return Some(bucket_min);
+                }
+            }
+
+            // Only reachable if `target_rank` overshoots due to rounding;
+            // fall back to the last (highest) bucket.
+            values.keys().next_back().copied()
+        })
+        .collect()
+}
+
+/// Scalar summary of a histogram. `mean`, `min`, `max`, `variance` and
+/// `stddev` are necessarily computed from bucket representatives, since the
+/// raw samples are gone once a histogram is bucketed: each bucket's stored
+/// minimum value stands in for its `count` entries. `sum` is the one field
+/// that doesn't need that approximation for functional histograms, since
+/// `Histogram::snapshot` already tracks the exact sum of accumulated
+/// samples; see `sum_of` below.
+#[derive(Serialize)]
+pub(crate) struct Stats {
+    pub count: u64,
+    pub sum: u64,
+    pub mean: Option<f64>,
+    pub min: Option<u64>,
+    pub max: Option<u64>,
+    pub variance: Option<f64>,
+    pub stddev: Option<f64>,
+}
+
+/// The exact `sum` for functional snapshots, or a bucket-representative
+/// approximation (`Σbucket_min * count`) for precomputed ones, which carry
+/// no real sum on the wire.
+fn sum_of(parsed: &Parsed) -> u64 {
+    match parsed {
+        Parsed::Functional { sum, .. } => *sum,
+        Parsed::Precomputed(values) => {
+            values.iter().map(|(&bucket_min, &count)| bucket_min * count).sum()
+        }
+    }
+}
+
+/// Computes count, sum, mean, min/max (of non-empty buckets), variance and
+/// standard deviation. `mean = Σ(bucket_min * count) / Σcount` and
+/// `variance = Σ(count * (bucket_min - mean)²) / Σcount` necessarily use
+/// bucket representatives, since individual sample values aren't
+/// recoverable.
+pub(crate) fn stats(parsed: &Parsed) -> Stats {
+    let values = parsed.values();
+    let count: u64 = values.values().sum();
+    let sum = sum_of(parsed);
+    let min = values.iter().find(|&(_, &c)| c > 0).map(|(&k, _)| k);
+    let max = values.iter().rev().find(|&(_, &c)| c > 0).map(|(&k, _)| k);
+
+    let mean = if count > 0 {
+        let representative_sum: f64 = values
+            .iter()
+            .map(|(&bucket_min, &c)| bucket_min as f64 * c as f64)
+            .sum();
+        Some(representative_sum / count as f64)
+    } else {
+        None
+    };
+
+    let variance = mean.map(|mean| {
+        let sum_sq_diff: f64 = values
+            .iter()
+            .map(|(&bucket_min, &c)| c as f64 * (bucket_min as f64 - mean).powi(2))
+            .sum();
+        sum_sq_diff / count as f64
+    });
+
+    Stats {
+        count,
+        sum,
+        mean,
+        min,
+        max,
+        variance,
+        stddev: variance.map(f64::sqrt),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_preserves_exact_functional_sum() {
+        let parsed = parse(r#"{"sum":123456,"values":{"5":2,"10":1}}"#).unwrap();
+        assert!(matches!(parsed, Parsed::Functional { sum: 123456, .. }));
+        assert_eq!(parsed.to_json(), r#"{"sum":123456,"values":{"5":2,"10":1}}"#);
+    }
+
+    #[test]
+    fn stats_uses_exact_sum_for_functional_snapshots() {
+        // The exact sum (e.g. 123456) need not match Σbucket_min*count
+        // (5*2 + 10*1 = 20) since real samples fall throughout a bucket,
+        // not exactly on its minimum; stats() must report the former.
+        let parsed = parse(r#"{"sum":123456,"values":{"5":2,"10":1}}"#).unwrap();
+        let stats = stats(&parsed);
+        assert_eq!(stats.sum, 123456);
+        assert_eq!(stats.count, 3);
+    }
+
+    #[test]
+    fn stats_falls_back_to_bucket_representative_sum_for_precomputed() {
+        let parsed = parse(r#"{"5":2,"10":1}"#).unwrap();
+        let stats = stats(&parsed);
+        assert_eq!(stats.sum, 5 * 2 + 10 * 1);
+    }
+
+    #[test]
+    fn merge_adds_exact_sums_for_functional_snapshots() {
+        let a = parse(r#"{"sum":10,"values":{"5":1}}"#).unwrap();
+        let b = parse(r#"{"sum":20,"values":{"5":1,"10":2}}"#).unwrap();
+        let merged = merge(&a, &b, None);
+        assert!(matches!(merged, Parsed::Functional { sum: 30, .. }));
+        assert_eq!(merged.values().get(&5), Some(&2));
+        assert_eq!(merged.values().get(&10), Some(&2));
+    }
+
+    #[test]
+    fn merge_precomputed_same_range_with_disjoint_occupied_buckets_succeeds() {
+        // Two shards of one Linear(0, 100, 10) histogram where one has a
+        // sample in bucket 0 and the other in bucket 90: they share no
+        // occupied bucket, but the bucketing is identical, so this must merge.
+        let a = parse(r#"{"0":1}"#).unwrap();
+        let b = parse(r#"{"90":1}"#).unwrap();
+        let merged = merge(&a, &b, Some((0, 100, 10)));
+        assert_eq!(merged.values().get(&0), Some(&1));
+        assert_eq!(merged.values().get(&90), Some(&1));
+    }
+
+    #[test]
+    #[should_panic(expected = "different ranges or bucket counts")]
+    fn merge_precomputed_rejects_bucket_outside_asserted_range() {
+        let a = parse(r#"{"0":1}"#).unwrap();
+        let b = parse(r#"{"500":1}"#).unwrap();
+        merge(&a, &b, Some((0, 100, 10)));
+    }
+
+    #[test]
+    #[should_panic(expected = "different ranges or bucket counts")]
+    fn merge_precomputed_rejects_mismatched_bucket_count() {
+        // Linear(0, 100, 10) has bucket boundaries every 10 units; a sample
+        // in bucket 34 could only come from a finer bucket_count (e.g. 50),
+        // so this must be rejected rather than silently merged.
+        let a = parse(r#"{"0":1}"#).unwrap();
+        let b = parse(r#"{"34":1}"#).unwrap();
+        merge(&a, &b, Some((0, 100, 10)));
+    }
+
+    #[test]
+    #[should_panic(expected = "requires the shared")]
+    fn merge_precomputed_without_range_panics() {
+        let a = parse(r#"{"0":1}"#).unwrap();
+        let b = parse(r#"{"0":1}"#).unwrap();
+        merge(&a, &b, None);
+    }
+
+    #[test]
+    fn percentiles_walks_buckets_to_the_target_rank() {
+        let mut values = BTreeMap::new();
+        values.insert(0, 1);
+        values.insert(10, 8);
+        values.insert(20, 1);
+
+        // p50 -> target rank ceil(0.5 * 10) = 5, reached within the bucket
+        // at 10 (running count 1 then 9).
+        // p100 -> target rank 10, the last entry in the highest bucket.
+        assert_eq!(percentiles(&values, &[0.0, 0.5, 1.0]), vec![Some(0), Some(10), Some(20)]);
+    }
+
+    #[test]
+    fn percentiles_of_empty_histogram_are_all_none() {
+        let values = BTreeMap::new();
+        assert_eq!(percentiles(&values, &[0.0, 0.5, 1.0]), vec![None, None, None]);
+    }
+
+    #[test]
+    fn percentiles_clamps_out_of_range_quantiles() {
+        let mut values = BTreeMap::new();
+        values.insert(0, 1);
+        values.insert(10, 1);
+        assert_eq!(percentiles(&values, &[-1.0, 2.0]), vec![Some(0), Some(10)]);
+    }
+}